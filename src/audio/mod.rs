@@ -1,43 +1,178 @@
-use cpal::{traits::{DeviceTrait, HostTrait, StreamTrait}, BufferSize, StreamConfig, SupportedBufferSize};
+use cpal::{traits::{DeviceTrait, HostTrait, StreamTrait}, BufferSize, SampleRate, StreamConfig, SupportedBufferSize};
+use wasm_bindgen::prelude::*;
+use gloo_utils::format::JsValueSerdeExt;
+use log::error;
+use serde::Serialize;
+
+// Requested output device/format. A `None` device, sample rate or channel count
+// falls back to the host's default, mirroring how `cpal`'s own examples pick a
+// device; a `None` buffer size uses the device's minimum buffer size, but at least 512.
+#[derive(Default)]
+pub struct AudioConfig {
+    pub device_name: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    pub buffer_size: Option<u32>
+}
+
+#[derive(Serialize)]
+pub struct SupportedAudioFormat {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32
+}
+
+#[derive(Serialize)]
+pub struct AudioDeviceInfo {
+    pub name: String,
+    pub supported_formats: Vec<SupportedAudioFormat>
+}
+
+// Lists the host's available output devices and the formats each one supports,
+// so a settings UI can offer a dropdown instead of always using the default.
+pub fn enumerate_output_devices() -> Vec<AudioDeviceInfo> {
+    let host = cpal::default_host();
+    let devices = match host.output_devices() {
+        Ok(devices) => devices,
+        Err(_err) => return Vec::new()
+    };
+    devices.filter_map(|device| {
+        let name = device.name().ok()?;
+        let supported_formats = device.supported_output_configs().ok()?
+            .map(|range| SupportedAudioFormat {
+                channels: range.channels(),
+                min_sample_rate: range.min_sample_rate().0,
+                max_sample_rate: range.max_sample_rate().0
+            })
+            .collect();
+        Some(AudioDeviceInfo { name, supported_formats })
+    }).collect()
+}
+
+#[wasm_bindgen]
+pub fn get_audio_output_devices() -> JsValue {
+    JsValue::from_serde(&enumerate_output_devices()).unwrap_or(JsValue::NULL)
+}
 
 pub struct AudioPlayer {
     output_stream: cpal::Stream,
+    input_stream: Option<cpal::Stream>,
     config: StreamConfig
 }
 
 impl AudioPlayer {
-    pub fn new<F: 'static + Send + Fn() -> f32>(get_sample: F) -> AudioPlayer {
+    // `push_input_sample` is only ever called when `enable_input` is true, but it's
+    // always taken so callers don't need two versions of `AudioPlayer::new`.
+    pub fn new<F, G>(config: AudioConfig, get_sample: F, enable_input: bool, push_input_sample: G) -> Result<AudioPlayer, String>
+    where
+        F: 'static + Send + Fn() -> f32,
+        G: 'static + Send + FnMut(f32)
+    {
         let host = cpal::default_host();
-        let output_device = match host.default_output_device() {
-            Some(device) => device,
-            None => panic!("No audio device found")
-        };
-        let supported_config = match output_device.default_output_config() {
-            Ok(config) => config,
-            Err(_err) => panic!("Default output config error: {}", _err)
-        };
-        let min_buffer_size = match supported_config.buffer_size() {
-            SupportedBufferSize::Range { min, .. } => BufferSize::Fixed(*min.max(&512)),
-            _ => BufferSize::Default
+        let output_device = Self::select_output_device(&host, config.device_name.as_deref())?;
+        let default_config = output_device.default_output_config()
+            .map_err(|err| format!("Default output config error: {}", err))?;
+
+        let channels = config.channels.unwrap_or_else(|| default_config.channels());
+        let sample_rate = config.sample_rate.unwrap_or_else(|| default_config.sample_rate().0);
+
+        let supported_configs: Vec<_> = output_device.supported_output_configs()
+            .map_err(|err| format!("Error querying supported output configs: {}", err))?
+            .collect();
+        let matching_config = supported_configs.iter().find(|range| {
+            range.channels() == channels
+                && sample_rate >= range.min_sample_rate().0
+                && sample_rate <= range.max_sample_rate().0
+        }).ok_or_else(|| format!("No supported output format for {} channel(s) at {} Hz", channels, sample_rate))?;
+
+        let buffer_size = match config.buffer_size {
+            Some(size) => BufferSize::Fixed(size),
+            None => match matching_config.buffer_size() {
+                // Higher buffer size means smoother audio but rougher frame rate and vice versa
+                SupportedBufferSize::Range { min, .. } => BufferSize::Fixed(*min.max(&512)),
+                _ => BufferSize::Default
+            }
         };
-        let config = StreamConfig {
-            channels: supported_config.channels(),
-            sample_rate: supported_config.sample_rate(),
-            buffer_size: min_buffer_size
+        let stream_config = StreamConfig {
+            channels,
+            sample_rate: SampleRate(sample_rate),
+            buffer_size
         };
+
         let output_data_fn = move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
             for (_, sample) in data.iter_mut().enumerate() {
                 *sample = get_sample();
             }
         };
-        let output_stream = match output_device.build_output_stream(&config, output_data_fn, Self::error, None) {
-            Ok(stream) => stream,
-            Err(err) => panic!("Error when building stream: {}", err)
+        let output_stream = output_device.build_output_stream(&stream_config, output_data_fn, Self::error, None)
+            .map_err(|err| format!("Error when building stream: {}", err))?;
+
+        // Microphone input is opt-in: cores that don't need it never open a capture
+        // device, and a missing/unusable device is recoverable rather than fatal
+        let input_stream = if enable_input {
+            match Self::build_input_stream(&host, stream_config.sample_rate.0, push_input_sample) {
+                Ok(stream) => Some(stream),
+                Err(err) => {
+                    error!("AudioPlayer: audio input unavailable, continuing without it: {}", err);
+                    None
+                }
+            }
+        } else {
+            None
         };
-        AudioPlayer {
+
+        Ok(AudioPlayer {
             output_stream,
-            config
+            input_stream,
+            config: stream_config
+        })
+    }
+
+    fn select_output_device(host: &cpal::Host, device_name: Option<&str>) -> Result<cpal::Device, String> {
+        if let Some(device_name) = device_name {
+            let named_device = host.output_devices().ok()
+                .and_then(|mut devices| devices.find(|device| device.name().map(|name| name == device_name).unwrap_or(false)));
+            if let Some(device) = named_device {
+                return Ok(device);
+            }
+            error!("AudioPlayer: output device '{}' not found, falling back to default", device_name);
         }
+        host.default_output_device().ok_or_else(|| "No audio output device found".to_string())
+    }
+
+    fn build_input_stream<G: 'static + Send + FnMut(f32)>(
+        host: &cpal::Host,
+        output_sample_rate: u32,
+        mut push_input_sample: G
+    ) -> Result<cpal::Stream, String> {
+        let input_device = host.default_input_device()
+            .ok_or_else(|| "No audio input device found".to_string())?;
+        let supported_config = input_device.default_input_config()
+            .map_err(|err| format!("Default input config error: {}", err))?;
+        let input_channels = supported_config.channels() as usize;
+        let input_sample_rate = supported_config.sample_rate().0;
+        let input_config: StreamConfig = supported_config.into();
+
+        // Resample linearly from the input device's rate to the core's output rate,
+        // since the mic and the speakers commonly run at different sample rates
+        let resample_step = input_sample_rate as f32 / output_sample_rate as f32;
+        let mut resample_pos = 0.0f32;
+        let input_data_fn = move |data: &[f32], _: &cpal::InputCallbackInfo| {
+            // Downmix to mono by taking the first channel of each input frame
+            let frames: Vec<f32> = data.chunks(input_channels).map(|frame| frame[0]).collect();
+            while (resample_pos as usize) < frames.len() {
+                let index = resample_pos as usize;
+                let frac = resample_pos.fract();
+                let current = frames[index];
+                let next = *frames.get(index + 1).unwrap_or(&current);
+                push_input_sample(current + (next - current) * frac);
+                resample_pos += resample_step;
+            }
+            resample_pos -= frames.len() as f32;
+        };
+
+        input_device.build_input_stream(&input_config, input_data_fn, Self::error, None)
+            .map_err(|err| format!("Error when building input stream: {}", err))
     }
 
     pub fn run(&self) {
@@ -45,6 +180,11 @@ impl AudioPlayer {
             Ok(_) => {},
             Err(err) => panic!("Stream play error: {}", err)
         };
+        if let Some(input_stream) = &self.input_stream {
+            if let Err(err) = input_stream.play() {
+                error!("AudioPlayer: input stream play error: {}", err);
+            }
+        }
     }
 
     pub fn get_sample_rate(&self) -> u32 {
@@ -1,5 +1,6 @@
 use crate::{Core, SyncModes};
 use crate::keymap::Keymap;
+use crate::metrics::Metrics;
 use wasm_bindgen::prelude::*;
 use error_iter::ErrorIter as _;
 use log::error;
@@ -13,6 +14,16 @@ use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
 use winit_input_helper::WinitInputHelper;
 use gloo_utils::format::JsValueSerdeExt;
+use instant::Instant;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+
+// Target rate for the simulation's fixed timestep in VSync mode, independent of
+// however fast or slow the window is actually being redrawn
+const FRAME_DURATION_SECS: f32 = 1.0 / 60.0;
+// Caps how many emulated frames a single tick can catch up on, so a stalled tab
+// (e.g. backgrounded) doesn't come back and burn through hours of missed frames
+const MAX_CATCHUP_FRAMES: f32 = 5.0;
 
 #[wasm_bindgen]
 extern "C" {
@@ -57,11 +68,13 @@ pub struct Display {
     width: usize,
     height: usize,
     keymap: Keymap,
-    sync_mode: SyncModes
+    sync_mode: SyncModes,
+    speed: Arc<Mutex<f32>>,
+    metrics: Arc<Mutex<Metrics>>
 }
 
 impl Display {
-    pub fn new(core: Arc<Mutex<impl Core>>, keymap: Keymap, sync_mode: SyncModes) -> Display {
+    pub fn new(core: Arc<Mutex<impl Core>>, keymap: Keymap, sync_mode: SyncModes, speed: Arc<Mutex<f32>>, metrics: Arc<Mutex<Metrics>>) -> Display {
         let core_temp = core.lock().unwrap();
         let width = core_temp.get_width();
         let height = core_temp.get_height();
@@ -71,7 +84,9 @@ impl Display {
             width,
             height,
             keymap,
-            sync_mode
+            sync_mode,
+            speed,
+            metrics
         }
     }
 
@@ -163,13 +178,59 @@ impl Display {
         let core = self.core.clone();
         let keymap = self.keymap.get_keys();
         let sync_mode = self.sync_mode;
+        let speed = self.speed.clone();
+        let metrics = self.metrics.clone();
+        // Wall-clock accumulator driving the fixed-timestep simulation, kept
+        // separate from the render loop so a slow or throttled redraw can't stall
+        // emulation and a fast monitor can't over-run it
+        let mut last_tick = Instant::now();
+        let mut time_accumulator: f32 = 0.0;
 
         event_loop.run(move |event, _, control_flow| {
+            // On wasm32 the event loop can't block between ticks (there's no thread to
+            // park), so drive the fixed timestep via Poll, which winit paces against
+            // requestAnimationFrame under the hood. Native instead blocks until the next
+            // tick is due below, so it doesn't busy-spin a CPU core for no benefit.
+            #[cfg(target_arch = "wasm32")]
+            if sync_mode == SyncModes::VSync {
+                *control_flow = ControlFlow::Poll;
+            }
+
+            // Advance the simulation independent of redraw cadence
+            if let Event::MainEventsCleared = event {
+                if sync_mode == SyncModes::VSync {
+                    let now = Instant::now();
+                    time_accumulator += now.duration_since(last_tick).as_secs_f32();
+                    last_tick = now;
+
+                    let speed = speed.lock().unwrap().clamp(0.1, 16.0);
+                    let frame_duration = FRAME_DURATION_SECS / speed;
+                    time_accumulator = time_accumulator.min(frame_duration * MAX_CATCHUP_FRAMES);
+
+                    let mut core = core.lock().unwrap();
+                    while time_accumulator >= frame_duration {
+                        core.run_frame();
+                        metrics.lock().unwrap().record_emulated_frame();
+                        time_accumulator -= frame_duration;
+                    }
+                    drop(core);
+
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        let until_next_tick = (frame_duration - time_accumulator).max(0.0);
+                        *control_flow = ControlFlow::WaitUntil(now + Duration::from_secs_f32(until_next_tick));
+                    }
+                }
+
+                window.request_redraw();
+            }
+
             // Draw the current frame
             if let Event::RedrawRequested(_) = event {
                 let core = core.lock().unwrap();
                 core.draw(pixels.frame_mut());
                 drop(core);
+                metrics.lock().unwrap().record_rendered_frame();
                 if let Err(err) = pixels.render() {
                     log_error("pixels.render", err);
                     *control_flow = ControlFlow::Exit;
@@ -215,12 +276,7 @@ impl Display {
                             on_key_released(i);
                         }
                     }
-                    if sync_mode == SyncModes::VSync {
-                        core.run_frame();
-                    }
                 });
-
-                window.request_redraw();
             }
         })
     }
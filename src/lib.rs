@@ -1,14 +1,17 @@
 mod audio;
 mod display;
+mod metrics;
 pub mod keymap;
 
 use wasm_bindgen::prelude::*;
 use crate::audio::AudioPlayer;
 use crate::display::Display;
 use crate::keymap::Keymap;
+use crate::metrics::Metrics;
 use clap::ValueEnum;
 use std::{future::Future, sync::{Arc, Mutex}};
 pub use winit::event::VirtualKeyCode;
+pub use crate::audio::AudioConfig;
 
 use std::path::PathBuf;
 use std::ffi::{OsStr, OsString};
@@ -26,6 +29,10 @@ pub enum SyncModes {
     AudioCallback
 }
 
+// Upper bound on instructions run to produce a single audio sample, guarding
+// against a core that never fills the sample queue
+const MAX_INSTRUCTIONS_PER_SAMPLE: u32 = 1_000_000;
+
 pub trait Core: Send + 'static {
     fn get_width(&self) -> usize;
     fn get_height(&self) -> usize;
@@ -39,30 +46,65 @@ pub trait Core: Send + 'static {
     fn run_inst(&mut self);
     fn run_frame(&mut self);
     fn get_sample(&mut self) -> f32;
+    fn reset(&mut self);
+    fn save_state(&self) -> Vec<u8>;
+    fn load_state(&mut self, state: &[u8]) -> Result<(), String>;
+    // No-op by default so cores that don't consume audio input are unaffected
+    fn push_input_sample(&mut self, _sample: f32) {}
 }
 
 #[wasm_bindgen]
 pub struct Frontend {
+    core: Arc<Mutex<dyn Core>>,
     display: display::Display,
-    audio_player: audio::AudioPlayer
+    audio_player: audio::AudioPlayer,
+    speed: Arc<Mutex<f32>>,
+    metrics: Arc<Mutex<Metrics>>
 }
 
 impl Frontend {
-    pub fn new<T: Core>(core: T, keymap: Keymap, sync_mode: SyncModes) -> Frontend {
+    pub fn new<T: Core>(core: T, keymap: Keymap, sync_mode: SyncModes, enable_audio_input: bool, audio_config: AudioConfig) -> Result<Frontend, String> {
         // Create Arcs to share the core between the audio and rendering threads
         let arc_parent = Arc::new(Mutex::new(core));
         let arc_child = arc_parent.clone();
+        let arc_input = arc_parent.clone();
+
+        // Shared emulation-speed multiplier, read by both the audio callback and
+        // the VSync render loop so `set_speed` affects whichever is driving execution
+        let speed = Arc::new(Mutex::new(1.0f32));
+        let speed_audio = speed.clone();
+        // Seconds-per-output-sample at speed 1.0, filled in once the audio device's
+        // sample rate is known below
+        let base_seconds_per_sample = Arc::new(Mutex::new(0.0f32));
+        let base_seconds_per_sample_audio = base_seconds_per_sample.clone();
+
+        let metrics = Arc::new(Mutex::new(Metrics::new()));
+        let metrics_audio = metrics.clone();
 
         let get_sample = move || {
             // Lock the mutex while generating samples in the audio thread
             let mut core = arc_child.lock().unwrap();
             match sync_mode {
                 SyncModes::AudioCallback => {
-                    // Run instructions until a new sample is ready and return that
-                    while core.get_sample_queue_length() == 0 {
+                    // Scale the emulated time per sample by the current speed so a
+                    // higher speed runs more instructions before each sample is ready
+                    let base = *base_seconds_per_sample_audio.lock().unwrap();
+                    let speed = *speed_audio.lock().unwrap();
+                    core.set_seconds_per_output_sample(base * speed);
+                    // Run instructions until a new sample is ready, bailing out after a
+                    // generous cap so a core that never produces a sample can't hang the
+                    // audio thread; record that case as an underrun
+                    let mut instructions_run = 0u32;
+                    while core.get_sample_queue_length() == 0 && instructions_run < MAX_INSTRUCTIONS_PER_SAMPLE {
                         core.run_inst();
+                        instructions_run += 1;
+                    }
+                    if core.get_sample_queue_length() == 0 {
+                        metrics_audio.lock().unwrap().record_audio_underrun();
+                        0.0
+                    } else {
+                        core.get_sample()
                     }
-                    core.get_sample()
                 },
                 SyncModes::VSync => {
                     // Audio is disabled with vsync, so just dump the samples and return 0
@@ -73,7 +115,14 @@ impl Frontend {
                 }
             }
         };
-        let audio_player = AudioPlayer::new(get_sample);
+        // Pushes captured microphone samples into the core under the same lock
+        // used by `get_sample`, so input and output never race each other
+        let push_input_sample = move |sample: f32| {
+            arc_input.lock().unwrap().push_input_sample(sample);
+        };
+        let audio_player = AudioPlayer::new(audio_config, get_sample, enable_audio_input, push_input_sample)?;
+
+        *base_seconds_per_sample.lock().unwrap() = 1.0 / audio_player.get_sample_rate() as f32;
 
         let arc_temp = arc_parent.clone();
         let mut core_temp = arc_temp.lock().unwrap();
@@ -81,12 +130,18 @@ impl Frontend {
         core_temp.set_num_output_channels(audio_player.get_num_channels());
         drop(core_temp);
 
-        let display = Display::new(arc_parent, keymap, sync_mode);
+        // Keep a handle to the core around so Frontend can lock it directly for
+        // reset/save/load, using the same mutex the audio thread runs against
+        let core = arc_parent.clone();
+        let display = Display::new(arc_parent, keymap, sync_mode, speed.clone(), metrics.clone());
 
-        Frontend {
+        Ok(Frontend {
+            core,
             display,
-            audio_player
-        }
+            audio_player,
+            speed,
+            metrics
+        })
     }
 }
 
@@ -97,6 +152,42 @@ impl Frontend {
         self.audio_player.run();
         self.display.run().await
     }
+
+    #[wasm_bindgen]
+    pub fn reset(&self) {
+        // Lock the same mutex used by `get_sample` so a reset can't race instruction execution
+        self.core.lock().unwrap().reset();
+    }
+
+    #[wasm_bindgen]
+    pub fn save_state(&self) -> Vec<u8> {
+        self.core.lock().unwrap().save_state()
+    }
+
+    #[wasm_bindgen]
+    pub fn load_state(&self, state: &[u8]) -> Result<(), String> {
+        self.core.lock().unwrap().load_state(state)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_speed(&self, speed: f32) {
+        *self.speed.lock().unwrap() = speed.clamp(0.1, 16.0);
+    }
+
+    #[wasm_bindgen]
+    pub fn get_fps(&self) -> f32 {
+        self.metrics.lock().unwrap().get_fps()
+    }
+
+    #[wasm_bindgen]
+    pub fn get_frame_count(&self) -> u64 {
+        self.metrics.lock().unwrap().get_frame_count()
+    }
+
+    #[wasm_bindgen]
+    pub fn get_audio_underruns(&self) -> u64 {
+        self.metrics.lock().unwrap().get_audio_underruns()
+    }
 }
 
 pub fn block_on<F: Future<Output = ()> + 'static>(fut: F) {
@@ -0,0 +1,67 @@
+use instant::Instant;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = wasm_imports)]
+    fn on_metrics_updated();
+}
+
+pub struct Metrics {
+    fps: f32,
+    fps_window_start: Instant,
+    fps_window_frames: usize,
+    frame_count: u64,
+    audio_underruns: u64
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            fps: 0.0,
+            fps_window_start: Instant::now(),
+            fps_window_frames: 0,
+            frame_count: 0,
+            audio_underruns: 0
+        }
+    }
+
+    // Called once per blit. FPS is recomputed once per back-to-back window of at least 1 second rather
+    // than smoothed frame-to-frame, matching how the Moa web UI's metrics panel reports it.
+    pub fn record_rendered_frame(&mut self) {
+        self.fps_window_frames += 1;
+        let elapsed = self.fps_window_start.elapsed().as_secs_f32();
+        if elapsed >= 1.0 {
+            self.fps = self.fps_window_frames as f32 / elapsed;
+            self.fps_window_frames = 0;
+            self.fps_window_start = Instant::now();
+            #[cfg(target_arch = "wasm32")]
+            on_metrics_updated();
+        }
+    }
+
+    // Called once per `core.run_frame()`, independent of how often (or seldom)
+    // a redraw actually happens, so this reflects actual emulated progress
+    pub fn record_emulated_frame(&mut self) {
+        self.frame_count += 1;
+    }
+
+    pub fn record_audio_underrun(&mut self) {
+        self.audio_underruns += 1;
+    }
+
+    pub fn get_fps(&self) -> f32 {
+        self.fps
+    }
+
+    pub fn get_frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn get_audio_underruns(&self) -> u64 {
+        self.audio_underruns
+    }
+}